@@ -0,0 +1,84 @@
+use crate::config::Environment;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+/// Resolve the session an activation belongs to: an explicit `--session`,
+/// falling back to `$USE_SESSION` (set by the shell wrapper once a session
+/// has been activated), falling back to a single shared session for users
+/// who only ever run one shell at a time.
+pub fn session_id(explicit: Option<&str>) -> String {
+    if let Some(id) = explicit {
+        return id.to_string();
+    }
+    std::env::var("USE_SESSION").unwrap_or_else(|_| "default".to_string())
+}
+
+fn state_dir() -> PathBuf {
+    dirs::home_dir().expect("Could not find home directory").join(".use_state")
+}
+
+fn state_path(session: &str) -> PathBuf {
+    state_dir().join(format!("{}.json", session))
+}
+
+/// The variable names an environment touches: its own `set`/`append`/
+/// `prepend`/`unset` entries, plus `PATH` when it has a `path` list.
+/// `set` is the already-resolved effective `set` map (inline entries merged
+/// over any `env_file` values).
+fn touched_vars(env: &Environment, set: &HashMap<String, String>) -> HashSet<String> {
+    let mut vars: HashSet<String> = set.keys().cloned().collect();
+    if let Some(append) = &env.append {
+        vars.extend(append.keys().cloned());
+    }
+    if let Some(prepend) = &env.prepend {
+        vars.extend(prepend.keys().cloned());
+    }
+    if env.path.is_some() {
+        vars.insert("PATH".to_string());
+    }
+    if let Some(unset) = &env.unset {
+        vars.extend(unset.iter().cloned());
+    }
+    vars
+}
+
+/// Snapshot the current value of every variable in `vars` that isn't
+/// already recorded in `state`, so activating several environments in a
+/// row (without deactivating in between) still restores back to the value
+/// from before the first activation rather than the most recent one.
+fn snapshot(vars: &HashSet<String>, state: &mut HashMap<String, Option<String>>) {
+    for var in vars {
+        state.entry(var.clone()).or_insert_with(|| std::env::var(var).ok());
+    }
+}
+
+/// Capture the prior value of every variable the activated environments
+/// touch and persist it as this session's restore snapshot.
+pub fn record_activation(
+    session: &str,
+    envs: &[(&Environment, HashMap<String, String>)],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut state = load_state(session).unwrap_or_default();
+    for (env, set) in envs {
+        snapshot(&touched_vars(env, set), &mut state);
+    }
+
+    std::fs::create_dir_all(state_dir())?;
+    std::fs::write(state_path(session), serde_json::to_string_pretty(&state)?)?;
+    Ok(())
+}
+
+/// Load the restore snapshot for `session`, if one exists.
+pub fn load_state(session: &str) -> Result<HashMap<String, Option<String>>, Box<dyn std::error::Error>> {
+    let content = std::fs::read_to_string(state_path(session))?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+/// Remove `session`'s restore snapshot once it has been applied.
+pub fn clear_state(session: &str) -> std::io::Result<()> {
+    let path = state_path(session);
+    if path.exists() {
+        std::fs::remove_file(path)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,211 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+pub static CONFIG_FILE_NAME: &str = ".useconfig.json";
+static CONFIG_FILE_EXAMPLE: &str = r#"
+{
+    "example": {
+        "display": "Name of the configuration",
+        "use": [
+            "other",
+            "configuration",
+            "names"
+        ],
+        "defer": [
+            "C:\\example\\path\\to\\script.bat",
+            "C:\\example\\other\\path\\to\\script.bat"
+        ],
+        "set": {
+            "EXAMPLE_VAR": "example value"
+        },
+        "append": {
+            "EXAMPLE_VAR_APPEND": "value appended to EXAMPLE_VAR_APPEND"
+        },
+        "prepend": {
+            "EXAMPLE_VAR_PREPEND": "value prepended to EXAMPLE_VAR_PREPEND"
+        },
+        "path": [
+            "C:\\example\\path\\to\\add\\to\\path",
+            "C:\\example\\other\\path\\to\\add\\to\\path"
+        ],
+        "go": "C:\\example\\path\\to\\go\\to",
+    },
+    "msvc2022": {
+        "display": "Microsoft Visual Studio 2022 - x64",
+        "defer": [
+            "C:\\Program Files\\Microsoft Visual Studio\\2022\\Professional\\VC\\Auxiliary\\Build\\vcvars64.bat"
+        ]
+    },
+    "qt6.8": {
+        "display": "Qt 6.8.2 - MSVC - x64",
+        "use": [
+            "msvc2022"
+        ],
+        "set": {
+            "QTDIR": "C:\\Qt\\6.8.2\\msvc2019_64\\"
+        },
+        "append": {
+            "CMAKE_PREFIX_PATH": "C:\\Qt\\6.8.2\\msvc2019_64\\"
+        },
+        "path": [
+            "C:\\Qt\\6.8.2\\msvc2019_64\\bin"
+        ]
+    },
+}
+"#;
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct Environment {
+    pub display: Option<String>,
+    pub defer: Option<Vec<String>>,
+    pub set: Option<HashMap<String, String>>,
+    pub append: Option<HashMap<String, String>>,
+    pub prepend: Option<HashMap<String, String>>,
+    pub path: Option<Vec<String>>,
+    #[serde(rename = "use")]
+    pub reuse: Option<Vec<String>>,
+    pub go: Option<String>,
+    /// Directory glob patterns that auto-activate this environment, used by
+    /// `--for-dir` for shell `cd`-hook integration.
+    pub when: Option<Vec<String>>,
+    /// Dotenv files to fold into `set` at resolution time, resolved relative
+    /// to the config file's directory.
+    pub env_file: Option<Vec<String>>,
+    /// Variables to remove from the environment, emitted as an `UNSET`
+    /// directive / `unset VAR` in shell codegen.
+    pub unset: Option<Vec<String>>,
+}
+
+/// Where an environment definition came from, so `list_environments` can
+/// annotate entries with their origin.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigSource {
+    /// The config file in the user's home directory.
+    Home(PathBuf),
+    /// A `.useconfig.json` found while walking up from the current directory.
+    Cwd(PathBuf),
+    /// The file passed explicitly via `--config`.
+    CommandArg(PathBuf),
+}
+
+impl ConfigSource {
+    /// The config file this source points at, so callers can resolve other
+    /// paths (like `env_file` entries) relative to its directory.
+    pub fn path(&self) -> &Path {
+        match self {
+            ConfigSource::Home(path) | ConfigSource::Cwd(path) | ConfigSource::CommandArg(path) => path,
+        }
+    }
+}
+
+impl std::fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigSource::Home(path) => write!(f, "{}", path.display()),
+            ConfigSource::Cwd(path) => write!(f, "{}", path.display()),
+            ConfigSource::CommandArg(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// Environments merged from every config file that applies, plus the source
+/// file each one was defined in.
+#[derive(Debug, Default)]
+pub struct Config {
+    pub environments: HashMap<String, Environment>,
+    pub origins: HashMap<String, ConfigSource>,
+}
+
+impl Config {
+    fn merge(&mut self, envs: HashMap<String, Environment>, source: ConfigSource) {
+        for (name, env) in envs {
+            self.origins.insert(name.clone(), source.clone());
+            self.environments.insert(name, env);
+        }
+    }
+}
+
+/// Create a config file in the home directory if it does not exist
+pub fn create_config_file(path: &str) {
+    // Open the file and writhe the CONFIG_FILE_CONTENT to it
+    let mut file = std::fs::File::create(path).expect("Failed to create file");
+    file.write_all(CONFIG_FILE_EXAMPLE.as_bytes()).expect("Failed to write to file");
+}
+
+/// Read a single congig file and return a map of environments
+fn read_config_file(file_path: &Path) -> Result<HashMap<String, Environment>, Box<dyn std::error::Error>> {
+    let file = File::open(file_path)?;
+    let reader = BufReader::new(file);
+    let config = serde_json::from_reader(reader)?;
+    Ok(config)
+}
+
+/// Walk up from the current directory to the filesystem root, collecting
+/// every `.useconfig.json` found along the way, ordered from the root down
+/// to the current directory (so the nearest one is merged last).
+fn discover_cwd_config_files() -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    if let Ok(mut dir) = std::env::current_dir() {
+        loop {
+            let candidate = dir.join(CONFIG_FILE_NAME);
+            if candidate.is_file() {
+                files.push(candidate);
+            }
+            if !dir.pop() {
+                break;
+            }
+        }
+    }
+    files.reverse();
+    files
+}
+
+/// Discover and merge every config file that applies, following the
+/// `Home < Cwd < CommandArg` precedence: the home config is the base, any
+/// `.useconfig.json` found walking up from the current directory overrides
+/// it (nearer directories winning over farther ones), and an explicit
+/// `--config <PATH>` overrides everything.
+pub fn discover_config(explicit_config: Option<&Path>) -> Result<Config, Box<dyn std::error::Error>> {
+    let mut config = Config::default();
+
+    let home_config = dirs::home_dir().expect("Could not find home directory").join(CONFIG_FILE_NAME);
+    if home_config.is_file() {
+        let envs = read_config_file(&home_config)?;
+        config.merge(envs, ConfigSource::Home(home_config));
+    }
+
+    for cwd_config in discover_cwd_config_files() {
+        let envs = read_config_file(&cwd_config)?;
+        config.merge(envs, ConfigSource::Cwd(cwd_config));
+    }
+
+    if let Some(explicit_config) = explicit_config {
+        let envs = read_config_file(explicit_config)?;
+        config.merge(envs, ConfigSource::CommandArg(explicit_config.to_path_buf()));
+    }
+
+    Ok(config)
+}
+
+/// Check that every `use` reference in the config points at an environment
+/// that actually exists, so a typo is reported once, up front, instead of
+/// panicking deep inside the resolution recursion.
+pub fn validate_config(envs: &HashMap<String, Environment>) -> Result<(), String> {
+    for (name, env) in envs.iter() {
+        if let Some(reuse) = env.reuse.as_ref() {
+            for used in reuse.iter() {
+                if !envs.contains_key(used.as_str()) {
+                    return Err(format!(
+                        "environment \"{}\" uses unknown environment \"{}\"",
+                        name, used
+                    ));
+                }
+            }
+        }
+    }
+    Ok(())
+}
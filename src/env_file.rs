@@ -0,0 +1,67 @@
+use crate::config::Environment;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Resolve `env`'s effective `set` map: variables loaded from its
+/// `env_file` entries (in order, each later file overriding the previous),
+/// followed by the inline `set` entries so a config's `set` can still
+/// override whatever a dotenv file provides.
+pub fn effective_set(env: &Environment, config_dir: Option<&Path>) -> HashMap<String, String> {
+    let mut merged = HashMap::new();
+
+    if let Some(env_files) = env.env_file.as_ref() {
+        for file in env_files {
+            let path = resolve_path(file, config_dir);
+            match parse_env_file(&path) {
+                Ok(vars) => merged.extend(vars),
+                Err(e) => eprintln!("Warning: could not read env file {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    if let Some(set) = env.set.as_ref() {
+        merged.extend(set.clone());
+    }
+
+    merged
+}
+
+fn resolve_path(file: &str, config_dir: Option<&Path>) -> PathBuf {
+    let path = Path::new(file);
+    if path.is_absolute() {
+        return path.to_path_buf();
+    }
+    match config_dir {
+        Some(dir) => dir.join(path),
+        None => path.to_path_buf(),
+    }
+}
+
+/// Parse a dotenv file: blank lines and `#` comments are skipped, each
+/// remaining line is split on the first `=`, and matching surrounding
+/// single or double quotes are trimmed from both key and value.
+fn parse_env_file(path: &Path) -> Result<HashMap<String, String>, Box<dyn std::error::Error>> {
+    let content = std::fs::read_to_string(path)?;
+    let mut vars = HashMap::new();
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        vars.insert(trim_quotes(key.trim()).to_string(), trim_quotes(value.trim()).to_string());
+    }
+
+    Ok(vars)
+}
+
+fn trim_quotes(value: &str) -> &str {
+    let bytes = value.as_bytes();
+    if bytes.len() >= 2 && (bytes[0] == b'"' || bytes[0] == b'\'') && bytes[0] == bytes[bytes.len() - 1] {
+        return &value[1..value.len() - 1];
+    }
+    value
+}
@@ -0,0 +1,147 @@
+use crate::config::Environment;
+use crate::env_file;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// An environment's `set`/`append`/`prepend`/`path`/`defer`/`go` fields
+/// after `${VAR}` interpolation, ready to hand to `print_environment` or
+/// `emit_for_shell`.
+pub struct Resolved {
+    pub set: HashMap<String, String>,
+    pub append: HashMap<String, String>,
+    pub prepend: HashMap<String, String>,
+    pub path: Vec<String>,
+    pub defer: Vec<String>,
+    pub go: Option<String>,
+}
+
+/// Single indirection for reading the process environment during
+/// interpolation, mirroring cargo's `Config::get_env`, so lookups stay in
+/// one place instead of scattered `std::env::var` calls.
+fn get_env(key: &str) -> Option<String> {
+    std::env::var(key).ok()
+}
+
+/// Resolve `env`'s interpolatable fields, expanding `${VAR}` references
+/// against `accumulator` (variables `set` by environments earlier in the
+/// chain) and the process environment. This environment's own `set` values
+/// are resolved first and folded into `accumulator`, so its other fields and
+/// later environments in the chain can reference them in turn.
+pub fn resolve_environment(
+    env: &Environment,
+    config_dir: Option<&Path>,
+    accumulator: &mut HashMap<String, String>,
+) -> Result<Resolved, String> {
+    let raw_set = env_file::effective_set(env, config_dir);
+    let set = resolve_set(raw_set, accumulator)?;
+
+    let append = expand_map(env.append.as_ref(), accumulator)?;
+    let prepend = expand_map(env.prepend.as_ref(), accumulator)?;
+    let path = expand_vec(env.path.as_ref(), accumulator)?;
+    let defer = expand_vec(env.defer.as_ref(), accumulator)?;
+    let go = env.go.as_deref().map(|value| expand(value, accumulator)).transpose()?;
+
+    Ok(Resolved { set, append, prepend, path, defer, go })
+}
+
+/// Expand a `set` map whose entries may reference each other (in either
+/// direction), by repeatedly expanding whatever entries currently resolve
+/// and feeding their values back into `accumulator` until no entry makes
+/// further progress. A genuinely undefined reference (or a cycle between
+/// two `set` entries) surfaces as the last error seen, in a stable
+/// (key-sorted) order.
+fn resolve_set(
+    raw_set: HashMap<String, String>,
+    accumulator: &mut HashMap<String, String>,
+) -> Result<HashMap<String, String>, String> {
+    let mut pending: Vec<(String, String)> = raw_set.into_iter().collect();
+    pending.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut resolved = HashMap::new();
+    loop {
+        let mut progressed = false;
+        let mut still_pending = Vec::new();
+        let mut last_error = None;
+
+        for (key, raw_value) in pending {
+            match expand(&raw_value, accumulator) {
+                Ok(expanded) => {
+                    accumulator.insert(key.clone(), expanded.clone());
+                    resolved.insert(key, expanded);
+                    progressed = true;
+                }
+                Err(e) => {
+                    last_error = Some(e);
+                    still_pending.push((key, raw_value));
+                }
+            }
+        }
+
+        if still_pending.is_empty() {
+            return Ok(resolved);
+        }
+        if !progressed {
+            return Err(last_error.unwrap());
+        }
+        pending = still_pending;
+    }
+}
+
+fn expand_map(map: Option<&HashMap<String, String>>, vars: &HashMap<String, String>) -> Result<HashMap<String, String>, String> {
+    let mut result = HashMap::new();
+    if let Some(map) = map {
+        for (key, value) in map {
+            result.insert(key.clone(), expand(value, vars)?);
+        }
+    }
+    Ok(result)
+}
+
+fn expand_vec(vec: Option<&Vec<String>>, vars: &HashMap<String, String>) -> Result<Vec<String>, String> {
+    let mut result = Vec::new();
+    if let Some(vec) = vec {
+        for item in vec {
+            result.push(expand(item, vars)?);
+        }
+    }
+    Ok(result)
+}
+
+/// Expand every `${VAR}` or `${VAR:-default}` reference in `value`, looking
+/// `VAR` up in `vars` first and then the process environment. A reference
+/// with no default to an undefined variable is an error.
+fn expand(value: &str, vars: &HashMap<String, String>) -> Result<String, String> {
+    let mut result = String::new();
+    let mut i = 0;
+    while i < value.len() {
+        if value[i..].starts_with("${") {
+            let end = value[i + 2..]
+                .find('}')
+                .map(|p| i + 2 + p)
+                .ok_or_else(|| format!("unterminated variable reference in \"{}\"", value))?;
+            let inner = &value[i + 2..end];
+            let (name, default) = match inner.split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (inner, None),
+            };
+
+            let resolved = vars.get(name).cloned().or_else(|| get_env(name));
+            match resolved.or_else(|| default.map(str::to_string)) {
+                Some(value) => result.push_str(&value),
+                None => {
+                    return Err(format!(
+                        "undefined variable \"{}\" referenced in \"{}\"",
+                        name, value
+                    ))
+                }
+            }
+
+            i = end + 1;
+        } else {
+            let ch = value[i..].chars().next().unwrap();
+            result.push(ch);
+            i += ch.len_utf8();
+        }
+    }
+    Ok(result)
+}
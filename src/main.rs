@@ -1,76 +1,26 @@
+mod activate;
+mod config;
+mod env_file;
+mod interpolate;
+mod resolve;
+mod shell;
+
 use clap::Parser;
+use clap::ValueEnum;
+use config::{Config, ConfigSource, Environment};
 use log::debug;
-use serde::Deserialize;
+use shell::Shell;
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::Write;
-use std::io::BufReader;
+use std::path::PathBuf;
 use std::str;
 
-static CONFIG_FILE_NAME: &str = ".useconfig.json";
-static CONFIG_FILE_EXAMPLE: &str = r#"
-{
-    "example": {
-        "display": "Name of the configuration",
-        "use": [
-            "other",
-            "configuration",
-            "names"
-        ],
-        "defer": [
-            "C:\\example\\path\\to\\script.bat",
-            "C:\\example\\other\\path\\to\\script.bat"
-        ],
-        "set": {
-            "EXAMPLE_VAR": "example value"
-        },
-        "append": {
-            "EXAMPLE_VAR_APPEND": "value appended to EXAMPLE_VAR_APPEND"
-        },
-        "prepend": {
-            "EXAMPLE_VAR_PREPEND": "value prepended to EXAMPLE_VAR_PREPEND"
-        },
-        "path": [
-            "C:\\example\\path\\to\\add\\to\\path",
-            "C:\\example\\other\\path\\to\\add\\to\\path"
-        ],
-        "go": "C:\\example\\path\\to\\go\\to",
-    },
-    "msvc2022": {
-        "display": "Microsoft Visual Studio 2022 - x64",
-        "defer": [
-            "C:\\Program Files\\Microsoft Visual Studio\\2022\\Professional\\VC\\Auxiliary\\Build\\vcvars64.bat"
-        ]
-    },
-    "qt6.8": {
-        "display": "Qt 6.8.2 - MSVC - x64",
-        "use": [
-            "msvc2022"
-        ],
-        "set": {
-            "QTDIR": "C:\\Qt\\6.8.2\\msvc2019_64\\"
-        },
-        "append": {
-            "CMAKE_PREFIX_PATH": "C:\\Qt\\6.8.2\\msvc2019_64\\"
-        },
-        "path": [
-            "C:\\Qt\\6.8.2\\msvc2019_64\\bin"
-        ]
-    },
-}
-"#;
-
-#[derive(Debug, Deserialize, Clone, PartialEq)]
-struct Environment {
-    display: Option<String>,
-    defer: Option<Vec<String>>,
-    set: Option<HashMap<String, String>>,
-    append: Option<HashMap<String, String>>,
-    prepend: Option<HashMap<String, String>>,
-    path: Option<Vec<String>>,
-    #[serde(rename = "use")]
-    reuse: Option<Vec<String>>,
-    go: Option<String>,
+/// Output format for a resolved environment: the shell-native statements
+/// from `emit_for_shell`, or the original generic directive lines for
+/// wrappers that still parse those themselves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Shell,
+    Directive,
 }
 
 #[derive(Parser, Debug)]
@@ -84,6 +34,31 @@ struct Args {
     /// Create a new config file
     #[clap(short, long)]
     create: bool,
+    /// Also load this config file, merging it in with the highest
+    /// precedence (overriding the home and any discovered project configs)
+    #[clap(long)]
+    config: Option<PathBuf>,
+    /// Resolve the environment that auto-activates for this directory and
+    /// print it, or nothing if no environment matches (for shell `cd` hooks)
+    #[clap(long)]
+    for_dir: Option<PathBuf>,
+    /// Shell to generate output for, auto-detected from $SHELL/$PSModulePath
+    /// when omitted
+    #[clap(long)]
+    shell: Option<Shell>,
+    /// Output format for the resolved environment
+    #[clap(long, value_enum, default_value = "shell")]
+    format: OutputFormat,
+    /// Undo every activation since the last deactivation instead of
+    /// activating an environment, restoring every variable they touched to
+    /// its value from before the first of them
+    #[clap(long)]
+    deactivate: bool,
+    /// Identifies the shell session activations belong to, so concurrent
+    /// shells don't clobber each other's restore state. Defaults to
+    /// $USE_SESSION, then to a single shared session
+    #[clap(long)]
+    session: Option<String>,
 }
 
 fn main() {
@@ -91,126 +66,165 @@ fn main() {
 
     let args = Args::parse();
 
-    let mut config_file_path = dirs::home_dir().expect("Could not find home directory");
-    config_file_path.push(CONFIG_FILE_NAME);
-    let config_file = config_file_path.to_str().unwrap();
+    let mut home_config_path = dirs::home_dir().expect("Could not find home directory");
+    home_config_path.push(config::CONFIG_FILE_NAME);
 
     if args.create {
-        create_config_file(config_file);
-        println!("Created {} file", config_file);
+        let home_config = home_config_path.to_str().unwrap();
+        config::create_config_file(home_config);
+        println!("Created {} file", home_config);
         std::process::exit(0);
     }
 
-    if !config_file_path.exists() {
-        print!("Error {} does not exist", config_file);
-        std::process::exit(1);
-    }
-    debug!("Find config file: {}", config_file);
-
-    let environments = match read_config_file(config_file) {
-        Ok(environments) => environments,
+    let loaded_config = match config::discover_config(args.config.as_deref()) {
+        Ok(loaded_config) => loaded_config,
         Err(e) => {
-            println!("Error reading {} file: {}", config_file, e);
+            eprintln!("Error reading config: {}", e);
             std::process::exit(1);
         }
     };
-    debug!("Read config file");
+    if loaded_config.environments.is_empty() {
+        eprintln!("Error: no {} file found", config::CONFIG_FILE_NAME);
+        std::process::exit(1);
+    }
+    debug!("Read config file(s)");
+
+    let Config { environments, origins } = loaded_config;
+
+    if let Err(e) = config::validate_config(&environments) {
+        eprintln!("Error in config: {}", e);
+        std::process::exit(1);
+    }
+
+    let shell = args.shell.unwrap_or_else(Shell::detect);
+    let session = activate::session_id(args.session.as_deref());
+
+    if args.deactivate {
+        match activate::load_state(&session) {
+            Ok(state) => {
+                shell::emit_restore(&state, shell);
+                if let Err(e) = activate::clear_state(&session) {
+                    debug!("Could not remove activation state for session {}: {}", session, e);
+                }
+            }
+            Err(e) => debug!("No activation state for session {}: {}", session, e),
+        }
+        std::process::exit(0);
+    }
+
+    if let Some(dir) = args.for_dir.as_ref() {
+        if let Some(env_name) = resolve::find_env_for_dir(dir, &environments) {
+            print_resolved_environment(env_name, &environments, &origins, args.format, shell, &session);
+        }
+        std::process::exit(0);
+    }
 
     if args.list || args.env_name.is_none() {
-        list_environments(environments);
+        list_environments(&environments, &origins);
         std::process::exit(0);
     }
 
     let env_name = args.env_name.unwrap();
     if !environments.contains_key(env_name.as_str()) {
-        println!("Error: Environment {} not found", env_name);
+        eprintln!("Error: Environment {} not found", env_name);
         std::process::exit(1);
     }
     debug!("Use environment: {}", env_name);
 
-    let env_names = list_all_envs_for(env_name, &environments);
+    print_resolved_environment(&env_name, &environments, &origins, args.format, shell, &session);
+}
+
+/// Resolve `env_name`'s full `use` chain and print each environment in it,
+/// in the requested output format
+fn print_resolved_environment(
+    env_name: &str,
+    environments: &HashMap<String, Environment>,
+    origins: &HashMap<String, ConfigSource>,
+    format: OutputFormat,
+    shell: Shell,
+    session: &str,
+) {
+    let env_names = match resolve::list_all_envs_for(env_name, environments) {
+        Ok(env_names) => env_names,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    };
     debug!("Setup environments: {:?}", env_names);
 
-    for env_name in env_names.iter().rev() {
+    let mut accumulator = HashMap::new();
+    let mut resolved = Vec::new();
+    for env_name in env_names.iter() {
         let env = environments.get(env_name).unwrap();
-        print_environment(env);
+        let config_dir = origins.get(env_name).and_then(|source| source.path().parent());
+        let env_resolved = match interpolate::resolve_environment(env, config_dir, &mut accumulator) {
+            Ok(env_resolved) => env_resolved,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        };
+        resolved.push((env, env_resolved));
     }
-}
 
-/// Create a config file in the home directory if it does not exist
-fn create_config_file(path: &str) {
-    // Open the file and writhe the CONFIG_FILE_CONTENT to it
-    let mut file = std::fs::File::create(path).expect("Failed to create file");
-    file.write_all(CONFIG_FILE_EXAMPLE.as_bytes()).expect("Failed to write to file");
-}
+    let activation_vars: Vec<_> = resolved.iter().map(|(env, r)| (*env, r.set.clone())).collect();
+    if let Err(e) = activate::record_activation(session, &activation_vars) {
+        debug!("Could not record activation state for session {}: {}", session, e);
+    }
 
-/// Read the congig file and return a map of environments
-fn read_config_file(file_path: &str) -> Result<HashMap<String, Environment>, Box<dyn std::error::Error>> {
-    let file = File::open(file_path)?;
-    let reader = BufReader::new(file);
-    let config = serde_json::from_reader(reader)?;
-    Ok(config)
+    for (env, env_resolved) in resolved.iter() {
+        match format {
+            OutputFormat::Shell => shell::emit_for_shell(env, shell, env_resolved),
+            OutputFormat::Directive => print_environment(env, env_resolved),
+        }
+    }
 }
 
-/// Function to list all environments in the config file
-fn list_environments(envs: HashMap<String, Environment>) {
+/// Function to list all environments in the config file, annotated with the
+/// file each one was defined in
+fn list_environments(envs: &HashMap<String, Environment>, origins: &HashMap<String, ConfigSource>) {
     // Get keys from configs map, sort then and print them
     let mut keys: Vec<_> = envs.keys().collect();
     keys.sort();
-    keys.iter().for_each(|key| println!("{}", key));
-}
-
-/// List all environment that should be used based on the environment name
-fn list_all_envs_for(
-    env_name: String,
-    envs: &HashMap<String, Environment>,
-) -> Vec<String> {
-    let mut env_names = vec![env_name.clone()];
-    let env = envs.get(env_name.as_str()).unwrap();
-
-    if let Some(reuse) = env.reuse.as_ref() {
-        for env_name in reuse.iter() {
-            let reuse_env_names = list_all_envs_for(env_name.clone(), envs);
-            // Add the environment to the list of environments to use
-            // Only if it is not already in the list
-            for reuse_env_name in reuse_env_names.iter() {
-                if !env_names.contains(reuse_env_name) {
-                    env_names.push(reuse_env_name.clone());
-                }
-            }
+    keys.iter().for_each(|key| {
+        match origins.get(key.as_str()) {
+            Some(source) => println!("{} ({})", key, source),
+            None => println!("{}", key),
         }
-    }
-
-    env_names
+    });
 }
 
 /// Print the environment to the console
-fn print_environment(env: &Environment) {
-    let print_map = |label: &str, map: &Option<HashMap<String, String>>| {
-        if let Some(map) = map {
-            for (key, value) in map {
-                println!("{}: {} = {}", label, key, value);
-            }
+///
+/// `resolved` holds the environment's `set`/`append`/`prepend`/`path`/
+/// `defer`/`go` fields after `env_file` merging and `${VAR}` interpolation,
+/// since the caller has already resolved those.
+fn print_environment(env: &Environment, resolved: &interpolate::Resolved) {
+    let print_map = |label: &str, map: &HashMap<String, String>| {
+        for (key, value) in map {
+            println!("{}: {} = {}", label, key, value);
         }
     };
 
-    let print_vec = |label: &str, vec: &Option<Vec<String>>| {
-        if let Some(vec) = vec {
-            for item in vec {
-                println!("{}: {}", label, item);
-            }
+    let print_vec = |label: &str, vec: &[String]| {
+        for item in vec {
+            println!("{}: {}", label, item);
         }
     };
 
     if let Some(display) = &env.display {
         println!("DISPLAY: {}", display);
     }
-    print_vec("DEFER", &env.defer);
-    print_map("SET", &env.set);
-    print_map("APPEND", &env.append);
-    print_map("PREPEND", &env.prepend);
-    print_vec("PATH", &env.path);
-    if let Some(go) = &env.go {
+    print_vec("DEFER", &resolved.defer);
+    print_map("SET", &resolved.set);
+    print_map("APPEND", &resolved.append);
+    print_map("PREPEND", &resolved.prepend);
+    print_vec("PATH", &resolved.path);
+    if let Some(unset) = &env.unset {
+        print_vec("UNSET", unset);
+    }
+    if let Some(go) = &resolved.go {
         println!("GO: {}", go);
     }
 }
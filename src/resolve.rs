@@ -0,0 +1,107 @@
+use crate::config::Environment;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// List all environments that should be used based on the environment name,
+/// in the order they should be applied (dependencies first, `env_name` last
+/// so its own values take precedence).
+pub fn list_all_envs_for(
+    env_name: &str,
+    envs: &HashMap<String, Environment>,
+) -> Result<Vec<String>, String> {
+    let mut env_names = Vec::new();
+    let mut visited = HashSet::new();
+    let mut on_stack = HashSet::new();
+    let mut path = Vec::new();
+    visit_env(env_name, envs, &mut env_names, &mut visited, &mut on_stack, &mut path)?;
+    Ok(env_names)
+}
+
+/// Depth-first visit of `env_name`'s `use` chain, used by `list_all_envs_for`.
+///
+/// `visited` holds names that are already fully expanded and can be skipped
+/// (this is what gives us the existing dedup behavior), while `on_stack`
+/// holds names on the current recursion path: finding one of those again
+/// means a cycle, which is reported as an explicit chain rather than
+/// overflowing the stack.
+fn visit_env(
+    env_name: &str,
+    envs: &HashMap<String, Environment>,
+    env_names: &mut Vec<String>,
+    visited: &mut HashSet<String>,
+    on_stack: &mut HashSet<String>,
+    path: &mut Vec<String>,
+) -> Result<(), String> {
+    if visited.contains(env_name) {
+        return Ok(());
+    }
+    if on_stack.contains(env_name) {
+        path.push(env_name.to_string());
+        return Err(format!(
+            "cycle detected in \"use\" references: {}",
+            path.join(" -> ")
+        ));
+    }
+
+    on_stack.insert(env_name.to_string());
+    path.push(env_name.to_string());
+
+    let env = envs.get(env_name).unwrap();
+    if let Some(reuse) = env.reuse.as_ref() {
+        for used in reuse.iter() {
+            visit_env(used, envs, env_names, visited, on_stack, path)?;
+        }
+    }
+
+    path.pop();
+    on_stack.remove(env_name);
+    visited.insert(env_name.to_string());
+    env_names.push(env_name.to_string());
+
+    Ok(())
+}
+
+/// Find the environment whose `when` glob matches `dir` most specifically,
+/// for shell `cd`-hook integration: a shell wrapper reports the directory it
+/// just entered via `--for-dir`, and we pick the best rule instead of the
+/// first one that happens to match.
+pub fn find_env_for_dir<'a>(dir: &Path, envs: &'a HashMap<String, Environment>) -> Option<&'a str> {
+    let dir = dir.to_string_lossy();
+    let mut best: Option<(&str, usize)> = None;
+
+    for (name, env) in envs.iter() {
+        let Some(whens) = env.when.as_ref() else {
+            continue;
+        };
+        for pattern in whens.iter() {
+            let Ok(glob) = glob::Pattern::new(pattern) else {
+                continue;
+            };
+            if !glob.matches(&dir) {
+                continue;
+            }
+            let specificity = literal_prefix_len(pattern);
+            // Ties (equally specific patterns) are broken by env name so the
+            // winner doesn't depend on HashMap iteration order.
+            let is_better = match best {
+                Some((best_name, best_len)) => {
+                    specificity > best_len || (specificity == best_len && name.as_str() < best_name)
+                }
+                None => true,
+            };
+            if is_better {
+                best = Some((name.as_str(), specificity));
+            }
+        }
+    }
+
+    best.map(|(name, _)| name)
+}
+
+/// Length of the literal (non-glob) prefix of `pattern`, used as a proxy for
+/// how specific a `when` rule is: the longer the fixed prefix, the more
+/// specific the match.
+fn literal_prefix_len(pattern: &str) -> usize {
+    pattern.chars().take_while(|c| !matches!(c, '*' | '?' | '[')).count()
+}
@@ -0,0 +1,187 @@
+use crate::config::Environment;
+use crate::interpolate::Resolved;
+use clap::ValueEnum;
+use std::collections::HashMap;
+
+/// Shells `emit_for_shell` knows how to generate eval-ready statements for.
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    #[value(name = "powershell")]
+    PowerShell,
+    Cmd,
+}
+
+impl Shell {
+    /// Guess the calling shell from the environment, falling back to `Bash`
+    /// on Unix-likes and `Cmd` on Windows.
+    pub fn detect() -> Shell {
+        if std::env::var_os("PSModulePath").is_some() {
+            return Shell::PowerShell;
+        }
+        if let Ok(shell) = std::env::var("SHELL") {
+            if shell.contains("zsh") {
+                return Shell::Zsh;
+            }
+            if shell.contains("bash") {
+                return Shell::Bash;
+            }
+        }
+        if cfg!(windows) {
+            Shell::Cmd
+        } else {
+            Shell::Bash
+        }
+    }
+
+    fn path_separator(self) -> char {
+        match self {
+            Shell::Bash | Shell::Zsh => ':',
+            Shell::PowerShell | Shell::Cmd => ';',
+        }
+    }
+}
+
+/// Translate an environment into statements ready to be `eval`'d by `shell`,
+/// replacing the generic `DISPLAY`/`SET`/`PATH` directive lines with
+/// shell-native syntax so no wrapper script has to re-parse our output.
+///
+/// `resolved` holds the environment's `set`/`append`/`prepend`/`path`/
+/// `defer`/`go` fields after `env_file` merging and `${VAR}` interpolation,
+/// since the caller has already resolved those.
+pub fn emit_for_shell(env: &Environment, shell: Shell, resolved: &Resolved) {
+    if let Some(display) = &env.display {
+        println!("{}", comment(shell, display));
+    }
+    for script in resolved.defer.iter() {
+        println!("{}", defer_command(shell, script));
+    }
+    for (key, value) in resolved.set.iter() {
+        println!("{}", set_command(shell, key, value));
+    }
+    for (key, value) in resolved.append.iter() {
+        println!("{}", concat_command(shell, key, value, true));
+    }
+    for (key, value) in resolved.prepend.iter() {
+        println!("{}", concat_command(shell, key, value, false));
+    }
+    for item in resolved.path.iter() {
+        println!("{}", concat_command(shell, "PATH", item, false));
+    }
+    if let Some(unset) = &env.unset {
+        for key in unset {
+            println!("{}", unset_command(shell, key));
+        }
+    }
+    if let Some(go) = &resolved.go {
+        println!("{}", go_command(shell, go));
+    }
+}
+
+/// Emit the statements that restore `state` (a variable -> prior value map,
+/// `None` meaning the variable was unset beforehand), undoing an activation.
+pub fn emit_restore(state: &HashMap<String, Option<String>>, shell: Shell) {
+    for (key, value) in state {
+        match value {
+            Some(value) => println!("{}", set_command(shell, key, value)),
+            None => println!("{}", unset_command(shell, key)),
+        }
+    }
+}
+
+fn comment(shell: Shell, text: &str) -> String {
+    match shell {
+        Shell::Cmd => format!(":: {}", text),
+        Shell::Bash | Shell::Zsh | Shell::PowerShell => format!("# {}", text),
+    }
+}
+
+/// Escape `value` so it survives being interpolated, verbatim, into the
+/// double-quoted string literals `set_command`/`concat_command`/
+/// `go_command`/`defer_command` build: without this, a value containing `"`,
+/// `$`, `` ` ``, or `\` either breaks the quoting or gets re-expanded by the
+/// shell that evaluates our output instead of being treated as inert data.
+fn escape_for_shell(shell: Shell, value: &str) -> String {
+    match shell {
+        Shell::Bash | Shell::Zsh => value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('$', "\\$")
+            .replace('`', "\\`"),
+        Shell::PowerShell => value
+            .replace('`', "``")
+            .replace('$', "`$")
+            .replace('"', "`\""),
+        // cmd has no escape character inside a quoted string; doubling is
+        // how `"` and `%` (its variable sigil) survive literally.
+        Shell::Cmd => value.replace('%', "%%").replace('"', "\"\""),
+    }
+}
+
+fn set_command(shell: Shell, key: &str, value: &str) -> String {
+    let value = escape_for_shell(shell, value);
+    match shell {
+        Shell::Bash | Shell::Zsh => format!("export {}=\"{}\"", key, value),
+        Shell::PowerShell => format!("$env:{} = \"{}\"", key, value),
+        Shell::Cmd => format!("set \"{}={}\"", key, value),
+    }
+}
+
+/// Build the statement that appends (or prepends) `value` to `key`,
+/// separator-aware: `PATH`-like variables use `:` on Unix shells and `;`
+/// elsewhere.
+fn concat_command(shell: Shell, key: &str, value: &str, append: bool) -> String {
+    let sep = shell.path_separator();
+    let value = escape_for_shell(shell, value);
+    match shell {
+        Shell::Bash | Shell::Zsh => {
+            if append {
+                format!("export {key}=\"${key}{sep}{value}\"")
+            } else {
+                format!("export {key}=\"{value}{sep}${key}\"")
+            }
+        }
+        Shell::PowerShell => {
+            if append {
+                format!("$env:{key} = \"$env:{key}{sep}{value}\"")
+            } else {
+                format!("$env:{key} = \"{value}{sep}$env:{key}\"")
+            }
+        }
+        Shell::Cmd => {
+            if append {
+                format!("set \"{key}=%{key}%{sep}{value}\"")
+            } else {
+                format!("set \"{key}={value}{sep}%{key}%\"")
+            }
+        }
+    }
+}
+
+fn unset_command(shell: Shell, key: &str) -> String {
+    match shell {
+        Shell::Bash | Shell::Zsh => format!("unset {}", key),
+        Shell::PowerShell => format!("Remove-Item Env:{}", key),
+        Shell::Cmd => format!("set \"{}=\"", key),
+    }
+}
+
+fn defer_command(shell: Shell, script: &str) -> String {
+    let script = escape_for_shell(shell, script);
+    match shell {
+        Shell::Bash | Shell::Zsh => format!("source \"{}\"", script),
+        Shell::PowerShell => format!("& \"{}\"", script),
+        Shell::Cmd => format!("call \"{}\"", script),
+    }
+}
+
+fn go_command(shell: Shell, path: &str) -> String {
+    let path = escape_for_shell(shell, path);
+    match shell {
+        Shell::Bash | Shell::Zsh => format!("cd \"{}\"", path),
+        Shell::PowerShell => format!("Set-Location \"{}\"", path),
+        Shell::Cmd => format!("cd /d \"{}\"", path),
+    }
+}